@@ -1,44 +1,91 @@
-use serde::{Deserialize, Serialize};
-use web_sys::HtmlInputElement;
+use std::collections::HashMap;
+
+use gloo_timers::callback::Timeout;
+use serde::Deserialize;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{
+    CanvasRenderingContext2d, ClipboardEvent, DragEvent, File, FileList, FileReader, HtmlCanvasElement,
+    HtmlImageElement, HtmlInputElement, MouseEvent,
+};
+use yew::html::Scope;
 use yew::prelude::*;
 use yew_agent::{Bridge, Bridged};
 
 use crate::{
-    services::{event_bus::EventBus, websocket::WebsocketService},
+    services::{
+        event_bus::{ConnectionState, EventBus, EventBusMsg},
+        websocket::{MsgTypes, Point, Presence, StatusUpdate, Stroke, WebSocketMessage, WebsocketService},
+    },
     User,
 };
 
+/// How long after the last keystroke a user is considered done typing.
+const TYPING_TIMEOUT_MS: u32 = 2_000;
+
+/// Deepest indent level rendered for a reply chain; deeper replies stay at
+/// this indent instead of marching further off the right edge.
+const MAX_REPLY_DEPTH: usize = 6;
+
+const STROKE_WIDTH: f64 = 3.0;
+const STROKE_COLORS: [&str; 5] = ["#ef4444", "#3b82f6", "#22c55e", "#f59e0b", "#111827"];
+
+/// Client-side cap on uploaded image files, enforced before we even start
+/// reading them.
+const MAX_UPLOAD_BYTES: f64 = 5.0 * 1024.0 * 1024.0;
+/// Images wider/taller than this are downscaled through an offscreen
+/// canvas before being sent.
+const MAX_IMAGE_DIMENSION: f64 = 800.0;
+
 pub enum Msg {
-    HandleMsg(String),
+    HandleMsg(EventBusMsg),
     SubmitMessage,
+    ReplyTo(String),
+    CancelReply,
+    PointerDown(MouseEvent),
+    PointerMove(MouseEvent),
+    PointerUp,
+    SetStrokeColor(String),
+    ClearBoard,
+    RepaintBoard,
+    FilesSelected(FileList),
+    ImageReady(String),
+    TypingInput,
+    TypingTimeout,
+    WindowBlurred,
+    WindowFocused,
+    OpenImage(String),
+    CloseImage,
 }
 
-#[derive(Deserialize)]
-struct MessageData {
-    from: String,
-    message: String,
+#[derive(Clone, Copy, PartialEq)]
+enum MessageKind {
+    Text,
+    Image,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum MsgTypes {
-    Users,
-    Register,
-    Message,
+impl Default for MessageKind {
+    fn default() -> Self {
+        MessageKind::Text
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WebSocketMessage {
-    message_type: MsgTypes,
-    data_array: Option<Vec<String>>,
-    data: Option<String>,
+#[derive(Deserialize)]
+struct MessageData {
+    from: String,
+    message: String,
+    id: Option<String>,
+    reply_to: Option<String>,
+    #[serde(skip)]
+    kind: MessageKind,
 }
 
 #[derive(Clone)]
 struct UserProfile {
     name: String,
     avatar: String,
+    presence: Presence,
+    /// `js_sys::Date::now()` timestamp (ms) of the last status change.
+    last_seen: Option<f64>,
 }
 
 pub struct Chat {
@@ -46,6 +93,19 @@ pub struct Chat {
     chat_input: NodeRef,
     wss: WebsocketService,
     messages: Vec<MessageData>,
+    connection_state: ConnectionState,
+    replying_to: Option<String>,
+    canvas_ref: NodeRef,
+    strokes: Vec<Stroke>,
+    current_stroke: Vec<Point>,
+    stroke_color: String,
+    drawing: bool,
+    file_input: NodeRef,
+    pending_images: Vec<String>,
+    is_typing: bool,
+    typing_timeout: Option<Timeout>,
+    /// Image currently shown full-size in the lightbox overlay, if any.
+    lightbox_image: Option<String>,
     _producer: Box<dyn Bridge<EventBus>>,
 }
 
@@ -59,49 +119,91 @@ impl Component for Chat {
             .context::<User>(Callback::noop())
             .expect("Context to be set");
 
-        let wss = WebsocketService::new();
         let username = user.username.borrow().clone();
-
-        let message = WebSocketMessage {
-            message_type: MsgTypes::Register,
-            data: Some(username),
-            data_array: None,
-        };
-
-        if let Ok(_) = wss.tx.clone().try_send(serde_json::to_string(&message).unwrap()) {
-            log::debug!("Registered successfully!");
-        }
+        let wss = WebsocketService::new(username);
 
         Self {
             users: vec![],
             messages: vec![],
             chat_input: NodeRef::default(),
             wss,
+            connection_state: ConnectionState::Connecting,
+            replying_to: None,
+            canvas_ref: NodeRef::default(),
+            strokes: vec![],
+            current_stroke: vec![],
+            stroke_color: STROKE_COLORS[0].to_string(),
+            drawing: false,
+            file_input: NodeRef::default(),
+            pending_images: vec![],
+            is_typing: false,
+            typing_timeout: None,
+            lightbox_image: None,
             _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::HandleMsg(s) => {
-                let msg: WebSocketMessage = serde_json::from_str(&s).unwrap();
+            Msg::HandleMsg(EventBusMsg::Status(state)) => {
+                self.connection_state = state;
+                true
+            }
+            Msg::HandleMsg(EventBusMsg::Frame(msg)) => {
                 match msg.message_type {
                     MsgTypes::Users => {
-                        let users_from_message = msg.data_array.unwrap_or_default();
-                        self.users = users_from_message
-                            .iter()
-                            .map(|u| UserProfile {
-                                name: u.clone(),
-                                avatar: format!("https://api.dicebear.com/8.x/adventurer-neutral/svg?seed={}", u),
+                        let names = msg.data_array.unwrap_or_default();
+                        // Reconcile by name instead of rebuilding wholesale,
+                        // so presence set from `Status` frames survives a
+                        // roster refresh.
+                        self.users = names
+                            .into_iter()
+                            .map(|name| {
+                                self.users.iter().find(|u| u.name == name).cloned().unwrap_or_else(|| UserProfile {
+                                    avatar: format!("https://api.dicebear.com/8.x/adventurer-neutral/svg?seed={}", name),
+                                    name,
+                                    presence: Presence::Online,
+                                    last_seen: None,
+                                })
                             })
                             .collect();
                         true
                     }
+                    MsgTypes::Status => {
+                        let status: StatusUpdate = serde_json::from_str(&msg.data.unwrap()).unwrap();
+                        if let Some(user) = self.users.iter_mut().find(|u| u.name == status.from) {
+                            user.presence = status.presence;
+                            user.last_seen = Some(js_sys::Date::now());
+                        }
+                        true
+                    }
                     MsgTypes::Message => {
                         let message_data: MessageData = serde_json::from_str(&msg.data.unwrap()).unwrap();
                         self.messages.push(message_data);
                         true
                     }
+                    MsgTypes::Image => {
+                        let mut message_data: MessageData = serde_json::from_str(&msg.data.unwrap()).unwrap();
+                        message_data.kind = MessageKind::Image;
+                        if let Some(pos) = self.pending_images.iter().position(|d| *d == message_data.message) {
+                            self.pending_images.remove(pos);
+                        }
+                        self.messages.push(message_data);
+                        true
+                    }
+                    MsgTypes::Draw => {
+                        let stroke: Stroke = serde_json::from_str(&msg.data.unwrap()).unwrap();
+                        if let Some(ctx2d) = self.canvas_2d_context() {
+                            draw_stroke(&ctx2d, &stroke);
+                        }
+                        self.strokes.push(stroke);
+                        false
+                    }
+                    MsgTypes::Clear => {
+                        self.strokes.clear();
+                        self.clear_canvas();
+                        false
+                    }
                     _ => false,
                 }
             }
@@ -111,22 +213,220 @@ impl Component for Chat {
                         message_type: MsgTypes::Message,
                         data: Some(input.value()),
                         data_array: None,
+                        codec: None,
+                        reply_to: self.replying_to.take(),
                     };
-                    if let Err(e) = self.wss.tx.clone().try_send(serde_json::to_string(&message).unwrap()) {
+                    if let Err(e) = self.wss.tx.clone().try_send(message) {
                         log::debug!("Send error: {:?}", e);
                     }
                     input.set_value("");
                 }
+                true
+            }
+            Msg::ReplyTo(id) => {
+                self.replying_to = Some(id);
+                true
+            }
+            Msg::CancelReply => {
+                self.replying_to = None;
+                true
+            }
+            Msg::PointerDown(e) => {
+                if let Some(canvas) = self.canvas_ref.cast::<HtmlCanvasElement>() {
+                    self.drawing = true;
+                    self.current_stroke = vec![point_from_event(&canvas, &e)];
+                }
+                false
+            }
+            Msg::PointerMove(e) => {
+                if self.drawing {
+                    if let Some(canvas) = self.canvas_ref.cast::<HtmlCanvasElement>() {
+                        let point = point_from_event(&canvas, &e);
+                        if let (Some(prev), Some(ctx2d)) = (self.current_stroke.last().cloned(), self.canvas_2d_context()) {
+                            draw_stroke(
+                                &ctx2d,
+                                &Stroke {
+                                    points: vec![prev, point.clone()],
+                                    color: self.stroke_color.clone(),
+                                    width: STROKE_WIDTH,
+                                },
+                            );
+                        }
+                        self.current_stroke.push(point);
+                    }
+                }
                 false
             }
+            Msg::PointerUp => {
+                if self.drawing {
+                    self.drawing = false;
+                    if self.current_stroke.len() >= 2 {
+                        let stroke = Stroke {
+                            points: std::mem::take(&mut self.current_stroke),
+                            color: self.stroke_color.clone(),
+                            width: STROKE_WIDTH,
+                        };
+                        // Don't append to `self.strokes` here — the server
+                        // echoes every `Draw` frame back, including our
+                        // own, and the `Draw` arm above is what appends to
+                        // history, same as `Message`/`Image` rely on their
+                        // echo instead of a local copy.
+                        let message = WebSocketMessage {
+                            message_type: MsgTypes::Draw,
+                            data: Some(serde_json::to_string(&stroke).unwrap()),
+                            data_array: None,
+                            codec: None,
+                            reply_to: None,
+                        };
+                        if let Err(e) = self.wss.tx.clone().try_send(message) {
+                            log::debug!("Send error: {:?}", e);
+                        }
+                    }
+                    self.current_stroke.clear();
+                }
+                false
+            }
+            Msg::SetStrokeColor(color) => {
+                self.stroke_color = color;
+                true
+            }
+            Msg::ClearBoard => {
+                self.strokes.clear();
+                self.clear_canvas();
+                let message = WebSocketMessage {
+                    message_type: MsgTypes::Clear,
+                    data: None,
+                    data_array: None,
+                    codec: None,
+                    reply_to: None,
+                };
+                if let Err(e) = self.wss.tx.clone().try_send(message) {
+                    log::debug!("Send error: {:?}", e);
+                }
+                false
+            }
+            Msg::RepaintBoard => {
+                self.repaint_canvas();
+                false
+            }
+            Msg::FilesSelected(files) => {
+                if let Some(file) = files.get(0) {
+                    read_image_file(file, ctx.link().clone());
+                }
+                false
+            }
+            Msg::ImageReady(data_url) => {
+                self.pending_images.push(data_url.clone());
+                let message = WebSocketMessage {
+                    message_type: MsgTypes::Image,
+                    data: Some(data_url),
+                    data_array: None,
+                    codec: None,
+                    reply_to: None,
+                };
+                if let Err(e) = self.wss.tx.clone().try_send(message) {
+                    log::debug!("Send error: {:?}", e);
+                }
+                true
+            }
+            Msg::TypingInput => {
+                if !self.is_typing {
+                    self.is_typing = true;
+                    send_status(&self.wss, Presence::Typing);
+                }
+                let link = ctx.link().clone();
+                self.typing_timeout = Some(Timeout::new(TYPING_TIMEOUT_MS, move || {
+                    link.send_message(Msg::TypingTimeout);
+                }));
+                false
+            }
+            Msg::TypingTimeout => {
+                self.is_typing = false;
+                send_status(&self.wss, Presence::Online);
+                false
+            }
+            Msg::WindowBlurred => {
+                self.is_typing = false;
+                self.typing_timeout = None;
+                send_status(&self.wss, Presence::Away);
+                false
+            }
+            Msg::WindowFocused => {
+                send_status(&self.wss, Presence::Online);
+                false
+            }
+            Msg::OpenImage(src) => {
+                self.lightbox_image = Some(src);
+                true
+            }
+            Msg::CloseImage => {
+                self.lightbox_image = None;
+                true
+            }
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if first_render {
+            self.repaint_canvas();
+
+            let link = ctx.link().clone();
+            let on_resize = Closure::wrap(Box::new(move |_: web_sys::Event| {
+                link.send_message(Msg::RepaintBoard);
+            }) as Box<dyn FnMut(_)>);
+            if let Some(window) = web_sys::window() {
+                let _ = window.add_event_listener_with_callback("resize", on_resize.as_ref().unchecked_ref());
+            }
+            on_resize.forget();
+
+            // Idle detection: the tab losing focus is the only producer of
+            // `Presence::Away`, with focus flipping the user back to online.
+            let blur_link = ctx.link().clone();
+            let on_blur = Closure::wrap(Box::new(move |_: web_sys::Event| {
+                blur_link.send_message(Msg::WindowBlurred);
+            }) as Box<dyn FnMut(_)>);
+            let focus_link = ctx.link().clone();
+            let on_focus = Closure::wrap(Box::new(move |_: web_sys::Event| {
+                focus_link.send_message(Msg::WindowFocused);
+            }) as Box<dyn FnMut(_)>);
+            if let Some(window) = web_sys::window() {
+                let _ = window.add_event_listener_with_callback("blur", on_blur.as_ref().unchecked_ref());
+                let _ = window.add_event_listener_with_callback("focus", on_focus.as_ref().unchecked_ref());
+            }
+            on_blur.forget();
+            on_focus.forget();
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let submit = ctx.link().callback(|_| Msg::SubmitMessage);
+        let on_reply = ctx.link().callback(Msg::ReplyTo);
+        let on_open_image = ctx.link().callback(Msg::OpenImage);
+        let cancel_reply = ctx.link().callback(|_| Msg::CancelReply);
         let (cur_user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
         let cur_username = cur_user.username.borrow().clone();
 
+        // Adjacency-list-to-tree: group message indices by the id they
+        // reply to, then walk depth-first from the roots (no reply_to).
+        // A reply whose parent hasn't arrived yet simply has no root to
+        // hang off of, so it stays buffered until its parent shows up.
+        let mut children: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, m) in self.messages.iter().enumerate() {
+            if let Some(parent) = &m.reply_to {
+                children.entry(parent.clone()).or_default().push(i);
+            }
+        }
+        let roots = self.messages.iter().enumerate().filter(|(_, m)| m.reply_to.is_none());
+
+        // Flatten the tree into a single (idx, depth) order up front so every
+        // bubble renders in one flat list — nesting each reply's markup
+        // inside its parent's margin-left div would let CSS margins compound
+        // across ancestors instead of staying capped at `MAX_REPLY_DEPTH`.
+        let mut message_order: Vec<(usize, usize)> = Vec::new();
+        for (i, _) in roots {
+            flatten_reply_order(&self.messages, &children, i, 0, &mut message_order);
+        }
+
         html! {
             <div class="flex w-screen h-screen overflow-hidden">
                 <aside class="w-64 bg-gray-100 border-r overflow-y-auto">
@@ -138,12 +438,33 @@ impl Component for Chat {
                         } else {
                             "bg-white"
                         };
+                        let dot_color = match u.presence {
+                            Presence::Online => "bg-green-500",
+                            Presence::Typing => "bg-violet-500",
+                            Presence::Away => "bg-gray-400",
+                        };
+                        let status_label = match u.presence {
+                            Presence::Typing => "typing…",
+                            Presence::Online => "online",
+                            Presence::Away => "away",
+                        };
+                        let last_seen_label = u.last_seen.map(|ms| format_last_seen(ms));
                         html! {
                             <div class={classes!("flex", "items-center", "m-3", "rounded-md", "p-2", "shadow-sm", user_style)}>
-                                <img class="w-10 h-10 rounded-full mr-3" src={u.avatar.clone()} />
+                                <div class="relative mr-3">
+                                    <img class="w-10 h-10 rounded-full" src={u.avatar.clone()} />
+                                    <span class={classes!("absolute", "bottom-0", "right-0", "w-3", "h-3", "rounded-full", "border-2", "border-white", dot_color)} />
+                                </div>
                                 <div>
                                     <div class="font-medium text-sm">{&u.name}</div>
-                                    <div class="text-xs text-gray-500">{"Hi there!"}</div>
+                                    <div class="text-xs text-gray-500">{status_label}</div>
+                                    {
+                                        if let Some(label) = last_seen_label {
+                                            html! { <div class="text-[11px] text-gray-400">{label}</div> }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
                                 </div>
                             </div>
                         }
@@ -152,46 +473,83 @@ impl Component for Chat {
 
                 <main class="flex-1 flex flex-col">
                     <header class="bg-white border-b p-4 text-xl font-bold">{"💬 Let's Chat!"}</header>
+                    {
+                        match self.connection_state {
+                            ConnectionState::Open => html! {},
+                            ConnectionState::Connecting => html! {
+                                <div class="bg-yellow-100 text-yellow-800 text-sm text-center py-1">{"Connecting…"}</div>
+                            },
+                            ConnectionState::Retrying => html! {
+                                <div class="bg-red-100 text-red-800 text-sm text-center py-1">{"Connection lost, retrying…"}</div>
+                            },
+                        }
+                    }
 
-                    <section class="flex-1 overflow-y-auto px-4 py-6 space-y-4 bg-gray-50">
-                        { for self.messages.iter().map(|m| {
-                            let is_self = m.from == cur_username;
-                            let avatar = self.users.iter().find(|u| u.name == m.from).map(|u| u.avatar.clone()).unwrap_or_default();
-                            let alignment = if is_self { "justify-end" } else { "justify-start" };
-                            let bubble_style = if is_self {
-                                "bg-green-500 text-white rounded-l-2xl rounded-br-2xl"
-                            } else {
-                                "bg-gray-200 text-gray-900 rounded-r-2xl rounded-bl-2xl"
-                            };
-
-                            html! {
-                                <div class={classes!("flex", alignment)}>
-                                    <div class={classes!("flex", "items-start", "space-x-2", "max-w-md", "p-3", bubble_style)}>
-                                        <img class="w-8 h-8 rounded-full" src={avatar} />
-                                        <div>
-                                            <div class="text-sm font-semibold">{&m.from}</div>
-                                            <div class="mt-1 text-sm">
-                                                {
-                                                    if m.message.ends_with(".gif") {
-                                                        html! { <img class="rounded-md max-w-[200px]" src={m.message.clone()} /> }
-                                                    } else {
-                                                        html! { <span>{&m.message}</span> }
-                                                    }
-                                                }
-                                            </div>
-                                        </div>
-                                    </div>
-                                </div>
-                            }
+                    <section
+                        class="flex-1 overflow-y-auto px-4 py-6 space-y-4 bg-gray-50"
+                        ondragover={Callback::from(|e: DragEvent| e.prevent_default())}
+                        ondrop={ctx.link().batch_callback(|e: DragEvent| {
+                            e.prevent_default();
+                            e.data_transfer().and_then(|dt| dt.files()).map(Msg::FilesSelected)
                         })}
+                    >
+                        { for message_order.iter().map(|&(i, depth)| render_message_node(
+                            &self.messages, &self.users, &cur_username, &on_reply, &on_open_image, i, depth,
+                        )) }
+                        { for self.pending_images.iter().map(|_| html! {
+                            <div class="flex justify-end">
+                                <div class="bg-green-100 text-green-700 text-xs italic rounded-2xl px-3 py-2">
+                                    {"Uploading image…"}
+                                </div>
+                            </div>
+                        }) }
                     </section>
 
-                    <footer class="p-4 bg-white border-t flex items-center space-x-3 sticky bottom-0">
+                    <footer class="relative p-4 bg-white border-t flex items-center space-x-3 sticky bottom-0">
+                        {
+                            if let Some(parent_id) = &self.replying_to {
+                                html! {
+                                    <div class="absolute bottom-16 left-4 right-4 bg-violet-50 border border-violet-200 rounded-md px-3 py-1 text-xs text-violet-700 flex items-center justify-between">
+                                        <span>{format!("Replying to #{}", parent_id)}</span>
+                                        <button onclick={cancel_reply} class="text-violet-500 hover:text-violet-700">{"✕"}</button>
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <input
+                            ref={self.file_input.clone()}
+                            type="file"
+                            accept="image/png,image/jpeg,image/webp"
+                            class="hidden"
+                            onchange={ctx.link().batch_callback(|e: Event| {
+                                let input: HtmlInputElement = e.target_unchecked_into();
+                                input.files().map(Msg::FilesSelected)
+                            })}
+                        />
+                        <button
+                            onclick={{
+                                let file_input = self.file_input.clone();
+                                Callback::from(move |_| {
+                                    if let Some(input) = file_input.cast::<HtmlInputElement>() {
+                                        input.click();
+                                    }
+                                })
+                            }}
+                            class="text-gray-500 hover:text-violet-600 w-10 h-10 flex items-center justify-center"
+                        >
+                            {"📎"}
+                        </button>
                         <input
                             ref={self.chat_input.clone()}
                             type="text"
                             placeholder="Type a message..."
                             class="flex-grow rounded-full border border-gray-300 p-2 px-4 focus:outline-none focus:ring-2 focus:ring-violet-400"
+                            oninput={ctx.link().callback(|_| Msg::TypingInput)}
+                            onpaste={ctx.link().batch_callback(|e: ClipboardEvent| {
+                                e.clipboard_data().and_then(|dt| dt.files()).map(Msg::FilesSelected)
+                            })}
                         />
                         <button onclick={submit} class="bg-violet-600 hover:bg-violet-700 text-white rounded-full w-10 h-10 flex items-center justify-center transition">
                             <svg fill="currentColor" viewBox="0 0 24 24" class="w-5 h-5">
@@ -200,7 +558,283 @@ impl Component for Chat {
                         </button>
                     </footer>
                 </main>
+
+                <aside class="w-80 bg-white border-l flex flex-col">
+                    <div class="flex items-center justify-between p-4 border-b">
+                        <span class="text-sm font-semibold">{"Whiteboard"}</span>
+                        <button
+                            onclick={ctx.link().callback(|_| Msg::ClearBoard)}
+                            class="text-xs text-gray-500 hover:text-red-600 border border-gray-300 rounded-full px-3 py-1"
+                        >
+                            {"Clear"}
+                        </button>
+                    </div>
+                    <canvas
+                        ref={self.canvas_ref.clone()}
+                        width="300"
+                        height="300"
+                        class="bg-gray-50 cursor-crosshair touch-none"
+                        onmousedown={ctx.link().callback(Msg::PointerDown)}
+                        onmousemove={ctx.link().callback(Msg::PointerMove)}
+                        onmouseup={ctx.link().callback(|_| Msg::PointerUp)}
+                        onmouseleave={ctx.link().callback(|_| Msg::PointerUp)}
+                    />
+                    <div class="flex items-center space-x-2 p-3 border-t">
+                        { for STROKE_COLORS.iter().map(|color| {
+                            let color = color.to_string();
+                            let is_active = color == self.stroke_color;
+                            let onclick = ctx.link().callback(move |_| Msg::SetStrokeColor(color.clone()));
+                            html! {
+                                <button
+                                    {onclick}
+                                    class={classes!("w-6", "h-6", "rounded-full", "border-2", if is_active { "border-gray-800" } else { "border-transparent" })}
+                                    style={format!("background-color: {}", color)}
+                                />
+                            }
+                        })}
+                    </div>
+                </aside>
+
+                {
+                    if let Some(src) = &self.lightbox_image {
+                        let close = ctx.link().callback(|_| Msg::CloseImage);
+                        html! {
+                            <div
+                                class="fixed inset-0 bg-black/80 flex items-center justify-center z-50 cursor-zoom-out"
+                                onclick={close}
+                            >
+                                <img class="max-w-[90vw] max-h-[90vh] rounded-md" src={src.clone()} />
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
             </div>
         }
     }
 }
+
+impl Chat {
+    fn canvas_2d_context(&self) -> Option<CanvasRenderingContext2d> {
+        self.canvas_ref
+            .cast::<HtmlCanvasElement>()?
+            .get_context("2d")
+            .ok()??
+            .dyn_into::<CanvasRenderingContext2d>()
+            .ok()
+    }
+
+    fn clear_canvas(&self) {
+        if let (Some(canvas), Some(ctx2d)) = (self.canvas_ref.cast::<HtmlCanvasElement>(), self.canvas_2d_context()) {
+            ctx2d.clear_rect(0.0, 0.0, canvas.width() as f64, canvas.height() as f64);
+        }
+    }
+
+    /// Replays every stroke from scratch; used on first mount and whenever
+    /// the canvas' pixel buffer may have been wiped by a resize.
+    fn repaint_canvas(&self) {
+        self.clear_canvas();
+        if let Some(ctx2d) = self.canvas_2d_context() {
+            for stroke in &self.strokes {
+                draw_stroke(&ctx2d, stroke);
+            }
+        }
+    }
+}
+
+/// Renders a `last_seen` timestamp (ms, from `js_sys::Date::now()`) as a
+/// relative "last seen Xs/Xm/Xh ago" label.
+fn format_last_seen(last_seen_ms: f64) -> String {
+    let elapsed_secs = ((js_sys::Date::now() - last_seen_ms) / 1000.0).max(0.0) as u64;
+    if elapsed_secs < 60 {
+        format!("last seen {}s ago", elapsed_secs)
+    } else if elapsed_secs < 3600 {
+        format!("last seen {}m ago", elapsed_secs / 60)
+    } else {
+        format!("last seen {}h ago", elapsed_secs / 3600)
+    }
+}
+
+/// Broadcasts `presence` as the current user's status. The server already
+/// knows who's sending, so the outgoing payload is just the `Presence`.
+fn send_status(wss: &WebsocketService, presence: Presence) {
+    let message = WebSocketMessage {
+        message_type: MsgTypes::Status,
+        data: Some(serde_json::to_string(&presence).unwrap()),
+        data_array: None,
+        codec: None,
+        reply_to: None,
+    };
+    if let Err(e) = wss.tx.clone().try_send(message) {
+        log::debug!("Send error: {:?}", e);
+    }
+}
+
+/// Reads `file` as a data URL, then hands it to `downscale_image` once
+/// loaded. Oversized files are rejected up front without ever being read.
+fn read_image_file(file: File, link: Scope<Chat>) {
+    if file.size() > MAX_UPLOAD_BYTES {
+        log::debug!("image too large: {} bytes", file.size());
+        return;
+    }
+
+    let reader = match FileReader::new() {
+        Ok(reader) => reader,
+        Err(_) => return,
+    };
+    let reader_clone = reader.clone();
+    let onload = Closure::wrap(Box::new(move |_: web_sys::Event| {
+        if let Some(data_url) = reader_clone.result().ok().and_then(|r| r.as_string()) {
+            downscale_image(data_url, link.clone());
+        }
+    }) as Box<dyn FnMut(_)>);
+    reader.set_onload(Some(onload.as_ref().unchecked_ref()));
+    onload.forget();
+
+    if reader.read_as_data_url(&file).is_err() {
+        log::debug!("failed to read image file");
+    }
+}
+
+/// Draws the loaded image onto an offscreen canvas sized to fit within
+/// `MAX_IMAGE_DIMENSION`, then sends the resulting data URL for upload.
+fn downscale_image(data_url: String, link: Scope<Chat>) {
+    let img = match HtmlImageElement::new() {
+        Ok(img) => img,
+        Err(_) => return,
+    };
+    let img_clone = img.clone();
+    let onload = Closure::wrap(Box::new(move |_: web_sys::Event| {
+        let (src_w, src_h) = (img_clone.natural_width() as f64, img_clone.natural_height() as f64);
+        let scale = (MAX_IMAGE_DIMENSION / src_w.max(src_h)).min(1.0);
+        let (dst_w, dst_h) = (src_w * scale, src_h * scale);
+
+        let Some(document) = web_sys::window().and_then(|w| w.document()) else { return };
+        let Ok(canvas) = document.create_element("canvas") else { return };
+        let Ok(canvas) = canvas.dyn_into::<HtmlCanvasElement>() else { return };
+        canvas.set_width(dst_w as u32);
+        canvas.set_height(dst_h as u32);
+
+        let Ok(Some(ctx2d)) = canvas.get_context("2d") else { return };
+        let Ok(ctx2d) = ctx2d.dyn_into::<CanvasRenderingContext2d>() else { return };
+        let _ = ctx2d.draw_image_with_html_image_element_and_dw_and_dh(&img_clone, 0.0, 0.0, dst_w, dst_h);
+
+        if let Ok(resized) = canvas.to_data_url_with_type("image/jpeg") {
+            link.send_message(Msg::ImageReady(resized));
+        }
+    }) as Box<dyn FnMut(_)>);
+    img.set_onload(Some(onload.as_ref().unchecked_ref()));
+    onload.forget();
+    img.set_src(&data_url);
+}
+
+fn point_from_event(canvas: &HtmlCanvasElement, e: &MouseEvent) -> Point {
+    let rect = canvas.get_bounding_client_rect();
+    Point {
+        x: e.client_x() as f64 - rect.left(),
+        y: e.client_y() as f64 - rect.top(),
+    }
+}
+
+fn draw_stroke(ctx2d: &CanvasRenderingContext2d, stroke: &Stroke) {
+    let mut points = stroke.points.iter();
+    let Some(first) = points.next() else { return };
+
+    ctx2d.set_stroke_style(&JsValue::from_str(&stroke.color));
+    ctx2d.set_line_width(stroke.width);
+    ctx2d.set_line_cap("round");
+    ctx2d.begin_path();
+    ctx2d.move_to(first.x, first.y);
+    for point in points {
+        ctx2d.line_to(point.x, point.y);
+    }
+    ctx2d.stroke();
+}
+
+/// Depth-first flattens the reply tree rooted at `idx` into `out` as
+/// `(message_idx, depth)` pairs, clamping `depth` at `MAX_REPLY_DEPTH`. Kept
+/// as one flat list (rather than nesting each reply inside its parent's
+/// markup) so the per-node indent stays capped instead of compounding
+/// through nested margin-left divs.
+fn flatten_reply_order(
+    messages: &[MessageData],
+    children: &HashMap<String, Vec<usize>>,
+    idx: usize,
+    depth: usize,
+    out: &mut Vec<(usize, usize)>,
+) {
+    out.push((idx, depth.min(MAX_REPLY_DEPTH)));
+    let next_depth = depth + 1;
+    for &child_idx in children.get(messages[idx].id.as_deref().unwrap_or_default()).into_iter().flatten() {
+        flatten_reply_order(messages, children, child_idx, next_depth, out);
+    }
+}
+
+/// Renders a single bubble for `messages[idx]` at the given (already
+/// clamped) `depth`.
+fn render_message_node(
+    messages: &[MessageData],
+    users: &[UserProfile],
+    cur_username: &str,
+    on_reply: &Callback<String>,
+    on_open_image: &Callback<String>,
+    idx: usize,
+    depth: usize,
+) -> Html {
+    let m = &messages[idx];
+    let is_self = m.from == cur_username;
+    let avatar = users.iter().find(|u| u.name == m.from).map(|u| u.avatar.clone()).unwrap_or_default();
+    let alignment = if is_self { "justify-end" } else { "justify-start" };
+    let bubble_style = if is_self {
+        "bg-green-500 text-white rounded-l-2xl rounded-br-2xl"
+    } else {
+        "bg-gray-200 text-gray-900 rounded-r-2xl rounded-bl-2xl"
+    };
+    let indent_px = depth * 24;
+
+    let reply_click = {
+        let on_reply = on_reply.clone();
+        let id = m.id.clone();
+        Callback::from(move |_| {
+            if let Some(id) = id.clone() {
+                on_reply.emit(id);
+            }
+        })
+    };
+
+    html! {
+        <div style={format!("margin-left: {}px", indent_px)}>
+            <div class={classes!("flex", alignment)}>
+                <div class={classes!("flex", "items-start", "space-x-2", "max-w-md", "p-3", bubble_style)}>
+                    <img class="w-8 h-8 rounded-full" src={avatar} />
+                    <div>
+                        <div class="text-sm font-semibold">{&m.from}</div>
+                        <div class="mt-1 text-sm">
+                            {
+                                if m.kind == MessageKind::Image {
+                                    let src = m.message.clone();
+                                    let open_full_size = {
+                                        let on_open_image = on_open_image.clone();
+                                        let src = src.clone();
+                                        Callback::from(move |_| on_open_image.emit(src.clone()))
+                                    };
+                                    html! { <img onclick={open_full_size} class="rounded-md max-w-[200px] cursor-zoom-in" src={src} /> }
+                                } else {
+                                    html! { <span>{&m.message}</span> }
+                                }
+                            }
+                        </div>
+                        {
+                            if m.id.is_some() {
+                                html! { <button onclick={reply_click} class="mt-1 text-xs text-gray-500 hover:text-violet-600">{"Reply"}</button> }
+                            } else {
+                                html! {}
+                            }
+                        }
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use yew_agent::{Agent, AgentLink, Context, HandlerId};
+
+use crate::services::websocket::WebSocketMessage;
+
+/// Connection lifecycle as seen from `WebsocketService`, relayed to every
+/// component subscribed to the bus so they can render connectivity state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Open,
+    Retrying,
+}
+
+/// Messages flowing over the bus: either a frame received from the server
+/// (already decoded from whichever codec it arrived in), or a connection
+/// state change from `WebsocketService`.
+#[derive(Clone, Debug)]
+pub enum EventBusMsg {
+    Frame(WebSocketMessage),
+    Status(ConnectionState),
+}
+
+pub struct EventBus {
+    link: AgentLink<EventBus>,
+    subscribers: HashSet<HandlerId>,
+}
+
+impl Agent for EventBus {
+    type Reach = Context<Self>;
+    type Message = ();
+    type Input = EventBusMsg;
+    type Output = EventBusMsg;
+
+    fn create(link: AgentLink<Self>) -> Self {
+        Self {
+            link,
+            subscribers: HashSet::new(),
+        }
+    }
+
+    fn update(&mut self, _msg: Self::Message) {}
+
+    fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
+        for sub in self.subscribers.iter() {
+            self.link.respond(*sub, msg.clone());
+        }
+    }
+
+    fn connected(&mut self, id: HandlerId) {
+        self.subscribers.insert(id);
+    }
+
+    fn disconnected(&mut self, id: HandlerId) {
+        self.subscribers.remove(&id);
+    }
+}
@@ -0,0 +1,277 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use futures::channel::mpsc::Sender;
+use futures::StreamExt;
+use gloo_timers::callback::Timeout;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{BinaryType, CloseEvent, ErrorEvent, MessageEvent, WebSocket};
+use yew_agent::Dispatched;
+
+use crate::services::event_bus::{ConnectionState, EventBus, EventBusMsg};
+
+const WS_URL: &str = "ws://127.0.0.1:8080/ws";
+const INITIAL_BACKOFF_MS: u32 = 500;
+const MAX_BACKOFF_MS: u32 = 30_000;
+
+/// Wire codec negotiated with the server at `Register` time. Everything
+/// after `Register` is sent with whatever codec the client announced;
+/// the server is expected to fall back to `Json` if it doesn't recognize
+/// the field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    Json,
+    Bincode,
+}
+
+/// Codec announced to the server on `Register`. If a send ever fails with
+/// this codec (e.g. the server doesn't support binary frames), the
+/// connection falls back to `Json` until the socket reconnects, at which
+/// point this codec is announced again.
+const PREFERRED_CODEC: Codec = Codec::Bincode;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MsgTypes {
+    Users,
+    Register,
+    Message,
+    Draw,
+    Clear,
+    Image,
+    Status,
+}
+
+/// A user's live activity, broadcast via `Status` frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    Online,
+    Away,
+    Typing,
+}
+
+/// Payload of an incoming `Status` frame: who changed, and to what. The
+/// outgoing direction only needs to carry the `Presence` itself (JSON in
+/// `WebSocketMessage::data`) since the server already knows who's sending.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StatusUpdate {
+    pub from: String,
+    pub presence: Presence,
+}
+
+/// A point on the shared whiteboard canvas, in canvas-local pixels.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// One continuous pen-down-to-pen-up stroke on the whiteboard. Carried as
+/// the JSON-encoded `data` of a `Draw` frame, same as `MessageData` is for
+/// `Message` frames.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Stroke {
+    pub points: Vec<Point>,
+    pub color: String,
+    pub width: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketMessage {
+    pub message_type: MsgTypes,
+    pub data_array: Option<Vec<String>>,
+    pub data: Option<String>,
+    /// Only ever set on a `Register` frame, which is always sent as JSON
+    /// so the server can read it before any codec has been agreed on.
+    pub codec: Option<Codec>,
+    /// Id of the message this one replies to, set by the client on send;
+    /// the server echoes it back on the resulting `MessageData`.
+    pub reply_to: Option<String>,
+}
+
+fn register_frame(username: &str, codec: Codec) -> WebSocketMessage {
+    WebSocketMessage {
+        message_type: MsgTypes::Register,
+        data: Some(username.to_string()),
+        data_array: None,
+        codec: Some(codec),
+        reply_to: None,
+    }
+}
+
+/// Encodes and hands `msg` to `ws`, using JSON for `Register` (the
+/// negotiation frame itself) and whatever `codec` currently holds for
+/// everything else. If a binary send fails, `codec` is downgraded to `Json`
+/// for the rest of this connection and the frame is retried as JSON.
+fn send_frame(ws: &WebSocket, msg: &WebSocketMessage, codec: &Rc<Cell<Codec>>) {
+    let active = if matches!(msg.message_type, MsgTypes::Register) {
+        Codec::Json
+    } else {
+        codec.get()
+    };
+    let result = match active {
+        Codec::Json => ws.send_with_str(&serde_json::to_string(msg).unwrap()),
+        Codec::Bincode => ws.send_with_u8_array(&bincode::serialize(msg).unwrap()),
+    };
+    if let Err(e) = result {
+        log::debug!("error sending data as {:?}: {:?}", active, e);
+        if active == Codec::Bincode {
+            codec.set(Codec::Json);
+            if let Err(e) = ws.send_with_str(&serde_json::to_string(msg).unwrap()) {
+                log::debug!("error sending data as json fallback: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Owns the socket's event-handler closures so a reconnect can replace them
+/// (dropping the previous set) instead of `.forget()`-ing a fresh batch
+/// every retry, which would leak one set of closures per reconnect for the
+/// life of the tab.
+struct SocketHandlers {
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+    _onerror: Closure<dyn FnMut(ErrorEvent)>,
+    _onopen: Closure<dyn FnMut(JsValue)>,
+    _onclose: Closure<dyn FnMut(CloseEvent)>,
+}
+
+pub struct WebsocketService {
+    pub tx: Sender<WebSocketMessage>,
+}
+
+impl WebsocketService {
+    /// Opens the socket and registers `username`. If the connection drops,
+    /// it is retried with exponential backoff (500ms, doubling, capped at
+    /// 30s) and `username` is re-registered automatically once it reopens.
+    pub fn new(username: String) -> Self {
+        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<WebSocketMessage>(1000);
+        let socket: Rc<RefCell<Option<WebSocket>>> = Rc::new(RefCell::new(None));
+        let backoff_ms = Rc::new(RefCell::new(INITIAL_BACKOFF_MS));
+        let codec = Rc::new(Cell::new(PREFERRED_CODEC));
+        let handlers: Rc<RefCell<Option<SocketHandlers>>> = Rc::new(RefCell::new(None));
+
+        connect(socket.clone(), backoff_ms, username, codec.clone(), handlers);
+
+        let out_socket = socket;
+        spawn_local(async move {
+            while let Some(msg) = in_rx.next().await {
+                if let Some(ws) = out_socket.borrow().as_ref() {
+                    send_frame(ws, &msg, &codec);
+                } else {
+                    log::debug!("dropping frame: not connected");
+                }
+            }
+        });
+
+        Self { tx: in_tx }
+    }
+}
+
+fn connect(
+    socket: Rc<RefCell<Option<WebSocket>>>,
+    backoff_ms: Rc<RefCell<u32>>,
+    username: String,
+    codec: Rc<Cell<Codec>>,
+    handlers: Rc<RefCell<Option<SocketHandlers>>>,
+) {
+    EventBus::dispatcher().send(EventBusMsg::Status(ConnectionState::Connecting));
+
+    let ws = WebSocket::new(WS_URL).expect("failed to create websocket");
+    ws.set_binary_type(BinaryType::Arraybuffer);
+
+    let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
+        let parsed = if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
+            serde_json::from_str::<WebSocketMessage>(&String::from(&txt)).ok()
+        } else if let Ok(buf) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
+            let bytes = js_sys::Uint8Array::new(&buf).to_vec();
+            bincode::deserialize::<WebSocketMessage>(&bytes).ok()
+        } else {
+            log::debug!("message event, received unknown data: {:?}", e.data());
+            None
+        };
+        match parsed {
+            Some(msg) => EventBus::dispatcher().send(EventBusMsg::Frame(msg)),
+            None => log::debug!("failed to decode frame"),
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+
+    let onerror_callback = Closure::wrap(Box::new(move |e: ErrorEvent| {
+        log::error!("websocket error: {}", e.message());
+    }) as Box<dyn FnMut(ErrorEvent)>);
+    ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+
+    let onopen_socket = socket.clone();
+    let onopen_backoff = backoff_ms.clone();
+    let onopen_username = username.clone();
+    let onopen_codec = codec.clone();
+    let onopen_callback = Closure::wrap(Box::new(move |_: JsValue| {
+        log::debug!("websocket connected");
+        *onopen_backoff.borrow_mut() = INITIAL_BACKOFF_MS;
+        // Re-announce the preferred codec on every fresh connection rather
+        // than carrying a downgrade forward — a failed send on the last
+        // socket doesn't mean this one (or the server) can't handle it.
+        onopen_codec.set(PREFERRED_CODEC);
+        EventBus::dispatcher().send(EventBusMsg::Status(ConnectionState::Open));
+        if let Some(ws) = onopen_socket.borrow().as_ref() {
+            send_frame(ws, &register_frame(&onopen_username, onopen_codec.get()), &onopen_codec);
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+    ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+
+    let onclose_socket = socket.clone();
+    let onclose_backoff = backoff_ms.clone();
+    let onclose_username = username.clone();
+    let onclose_codec = codec.clone();
+    let onclose_handlers = handlers.clone();
+    let onclose_callback = Closure::wrap(Box::new(move |_: CloseEvent| {
+        // Drop the dead socket immediately instead of leaving it in place
+        // for the whole backoff delay — `WebSocket::send` on a closed
+        // socket doesn't error, it silently discards. With the socket gone,
+        // the send loop logs each frame sent during that window as dropped;
+        // such frames are not resent after the reconnect.
+        *onclose_socket.borrow_mut() = None;
+        schedule_reconnect(
+            onclose_socket.clone(),
+            onclose_backoff.clone(),
+            onclose_username.clone(),
+            onclose_codec.clone(),
+            onclose_handlers.clone(),
+        );
+    }) as Box<dyn FnMut(CloseEvent)>);
+    ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+
+    // Replacing the previous `Some(...)` here drops its closures (instead
+    // of leaking them via `.forget()`) now that the new ones are attached.
+    *handlers.borrow_mut() = Some(SocketHandlers {
+        _onmessage: onmessage_callback,
+        _onerror: onerror_callback,
+        _onopen: onopen_callback,
+        _onclose: onclose_callback,
+    });
+    *socket.borrow_mut() = Some(ws);
+}
+
+fn schedule_reconnect(
+    socket: Rc<RefCell<Option<WebSocket>>>,
+    backoff_ms: Rc<RefCell<u32>>,
+    username: String,
+    codec: Rc<Cell<Codec>>,
+    handlers: Rc<RefCell<Option<SocketHandlers>>>,
+) {
+    let delay = *backoff_ms.borrow();
+    EventBus::dispatcher().send(EventBusMsg::Status(ConnectionState::Retrying));
+    log::debug!("reconnecting in {}ms", delay);
+
+    *backoff_ms.borrow_mut() = (delay * 2).min(MAX_BACKOFF_MS);
+
+    Timeout::new(delay, move || {
+        connect(socket, backoff_ms, username, codec, handlers);
+    })
+    .forget();
+}